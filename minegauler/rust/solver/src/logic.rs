@@ -2,33 +2,64 @@
 
 use utils::{Board, BoardProbs, CellContents, Coord};
 
-use std::collections::HashSet;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
 
 // -----------------------------------------------------------------------------
 // Types
 
 /// A number on a board.
 #[cfg_attr(test, derive(Debug))]
-struct Number<'a> {
+struct Number {
     /// Value of the number, as shown.
     value: u32,
     /// Coordinate of the cell the number is shown in.
     coord: Coord,
     /// Neighbouring clickable cells.
     nbrs: HashSet<Coord>,
-    /// Groups the number has next to it.
-    groups: Vec<&'a Group<'a>>,
+    /// Indices into the `groups` vec of the groups the number has next to it.
+    groups: Vec<usize>,
 }
 
+/// A maximal set of unclicked cells that neighbour exactly the same numbers.
 #[cfg_attr(test, derive(Debug))]
-struct Group<'a> {
+struct Group {
+    /// Number of cells in the group.
     max: u32,
-    numbers: Vec<&'a Number<'a>>,
+    /// Cells making up the group.
+    cells: HashSet<Coord>,
+    /// Indices into the `numbers` vec of the numbers constraining the group.
+    numbers: Vec<usize>,
 }
 
+/// A single valid assignment of mine-counts to every group.
 #[cfg_attr(test, derive(Debug))]
 struct Config {
-    _a: (),
+    /// Number of mines in each group, index-aligned with the `groups` vec.
+    counts: Vec<u32>,
+    /// Number of ways this assignment can occur, i.e. the product of
+    /// `C(group.max, count)` over all groups.
+    weight: u64,
+}
+
+/// How a cell was deduced to be safe or a mine.
+#[derive(Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub enum Deduction {
+    /// Deduced directly from a single number, without needing to enumerate
+    /// configs.
+    Trivial,
+    /// Only provable once every border config has been enumerated.
+    Logic,
+}
+
+/// The result of fully solving a board.
+pub struct Solution {
+    pub probs: BoardProbs,
+    /// Cells that are safe in every valid config, and why.
+    pub safe: HashMap<Coord, Deduction>,
+    /// Cells that are mines in every valid config, and why.
+    pub mines: HashMap<Coord, Deduction>,
 }
 
 // -----------------------------------------------------------------------------
@@ -46,17 +77,15 @@ fn find_numbers(board: &Board) -> Vec<Number> {
         }
     });
     for (coord, orig_value) in iter_num_cells {
-        let all_nbrs = board.get_neighbours(coord);
-        // Reduce number value based on neighbouring mines.
+        // Reduce number value based on neighbouring mines, treating
+        // user-flagged cells the same as revealed mines.
         let value = orig_value;
-        let mines: u32 = all_nbrs
-            .iter()
-            .filter_map(|c| {
-                if let &CellContents::Mine(n) = board.cell(*c) {
-                    Some(n)
-                } else {
-                    None
-                }
+        let mines: u32 = board
+            .iter_neighbours(coord)
+            .filter_map(|c| match board.cell(c) {
+                CellContents::Mine(n) => Some(*n),
+                CellContents::Flagged => Some(1),
+                _ => None,
             })
             .sum();
         if value < mines {
@@ -67,8 +96,8 @@ fn find_numbers(board: &Board) -> Vec<Number> {
         }
         let value = value - mines;
         // Get the neighbouring clickable cells.
-        let clickable_nbrs = all_nbrs
-            .into_iter()
+        let clickable_nbrs = board
+            .iter_neighbours(coord)
             .filter(|c| *board.cell(*c) == CellContents::Unclicked)
             .collect::<HashSet<Coord>>();
 
@@ -82,16 +111,195 @@ fn find_numbers(board: &Board) -> Vec<Number> {
     nums
 }
 
-fn find_groups<'a>(numbers: &Vec<Number<'a>>) -> Vec<Group<'a>> {
-    vec![]
+/// Partition the unclicked cells bordering `numbers` into maximal groups.
+///
+/// Two cells belong to the same group iff they neighbour exactly the same
+/// set of numbers. Each number is updated in place with the indices of the
+/// groups it touches.
+fn find_groups(numbers: &mut Vec<Number>) -> Vec<Group> {
+    // Map each bordering cell to the indices of the numbers it neighbours.
+    let mut cell_to_nums: HashMap<Coord, Vec<usize>> = HashMap::new();
+    for (i, num) in numbers.iter().enumerate() {
+        for &cell in &num.nbrs {
+            cell_to_nums.entry(cell).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    // Cells with an identical (sorted) set of numbers belong to one group.
+    let mut cells_by_nums: HashMap<Vec<usize>, HashSet<Coord>> = HashMap::new();
+    for (cell, mut num_indices) in cell_to_nums {
+        num_indices.sort_unstable();
+        cells_by_nums
+            .entry(num_indices)
+            .or_insert_with(HashSet::new)
+            .insert(cell);
+    }
+
+    let mut groups = Vec::new();
+    for (num_indices, cells) in cells_by_nums {
+        let group_idx = groups.len();
+        for &num_idx in &num_indices {
+            numbers[num_idx].groups.push(group_idx);
+        }
+        groups.push(Group {
+            max: cells.len() as u32,
+            cells,
+            numbers: num_indices,
+        });
+    }
+    groups
+}
+
+/// Return `n choose k`.
+fn binomial(n: u32, k: u32) -> u64 {
+    if k > n {
+        return 0;
+    }
+    let k = min(k, n - k);
+    let mut result: u64 = 1;
+    for i in 0..k {
+        result = result * (n - i) as u64 / (i + 1) as u64;
+    }
+    result
+}
+
+/// Check whether the counts assigned so far are consistent with `numbers`,
+/// i.e. no number that is already fully assigned has the wrong total.
+fn is_consistent(group_idx: usize, numbers: &[Number], groups: &[Group], counts: &[Option<u32>]) -> bool {
+    for &num_idx in &groups[group_idx].numbers {
+        let number = &numbers[num_idx];
+        if number.groups.iter().all(|&gi| counts[gi].is_some()) {
+            let sum: u32 = number.groups.iter().map(|&gi| counts[gi].unwrap()).sum();
+            if sum != number.value {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Recursively assign mine-counts to groups from `group_idx` onwards,
+/// pruning branches that already violate a fully-assigned number.
+fn assign_groups(
+    group_idx: usize,
+    numbers: &[Number],
+    groups: &[Group],
+    counts: &mut Vec<Option<u32>>,
+    configs: &mut Vec<Config>,
+) {
+    if group_idx == groups.len() {
+        let counts: Vec<u32> = counts.iter().map(|c| c.unwrap()).collect();
+        let weight = groups
+            .iter()
+            .zip(&counts)
+            .map(|(g, &k)| binomial(g.max, k))
+            .product();
+        configs.push(Config { counts, weight });
+        return;
+    }
+    for k in 0..=groups[group_idx].max {
+        counts[group_idx] = Some(k);
+        if is_consistent(group_idx, numbers, groups, counts) {
+            assign_groups(group_idx + 1, numbers, groups, counts, configs);
+        }
+    }
+    counts[group_idx] = None;
 }
 
-fn find_configs<'a>(numbers: &Vec<Number<'a>>, groups: &Vec<Group<'a>>) -> Vec<Config> {
-    vec![]
+/// Enumerate every valid mine-count assignment over `groups`.
+fn find_configs(numbers: &[Number], groups: &[Group]) -> Vec<Config> {
+    // A number with no neighbouring groups is already fully assigned with a
+    // count of 0 - if its value isn't 0 then the board has no solutions.
+    if numbers.iter().any(|n| n.groups.is_empty() && n.value != 0) {
+        return vec![];
+    }
+    let mut configs = Vec::new();
+    let mut counts = vec![None; groups.len()];
+    assign_groups(0, numbers, groups, &mut counts, &mut configs);
+    configs
 }
 
-fn find_probs(board: &Board, configs: &Vec<Config>) -> BoardProbs {
-    BoardProbs::new(0, 0)
+/// The unclicked cells that don't border any number, i.e. aren't covered by
+/// any group.
+fn find_outer_cells(board: &Board, groups: &[Group]) -> HashSet<Coord> {
+    let bordering: HashSet<Coord> = groups.iter().flat_map(|g| g.cells.iter().copied()).collect();
+    board
+        .iter_cells()
+        .filter_map(|(c, v)| {
+            if *v == CellContents::Unclicked && !bordering.contains(&c) {
+                Some(c)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Turn a set of configs into a per-cell probability of being a mine.
+///
+/// If `total_mines` is given, each config is additionally weighted by
+/// `C(outer_cells, total_mines - border_total)`, accounting for how the
+/// mines not on the border distribute among the outer cells, and those outer
+/// cells are given a uniform probability based on the expected number of
+/// mines left over once the border is accounted for.
+fn find_probs(
+    board: &Board,
+    groups: &[Group],
+    configs: &[Config],
+    total_mines: Option<u32>,
+) -> BoardProbs {
+    let mut probs = BoardProbs::new(board.x_size(), board.y_size());
+    let outer_cells = find_outer_cells(board, groups);
+    let num_outer = outer_cells.len() as u32;
+
+    // Pair each config with its border mine-total and its overall weight,
+    // taking the outer cells into account if a mine count was given.
+    let weighted: Vec<(u64, u32)> = configs
+        .iter()
+        .map(|c| {
+            let border_total: u32 = c.counts.iter().sum();
+            let weight = match total_mines {
+                None => c.weight,
+                Some(total) if border_total <= total => {
+                    c.weight * binomial(num_outer, total - border_total)
+                }
+                Some(_) => 0,
+            };
+            (weight, border_total)
+        })
+        .collect();
+    let total_weight: u64 = weighted.iter().map(|(w, _)| w).sum();
+    if total_weight == 0 {
+        return probs;
+    }
+
+    for (group_idx, group) in groups.iter().enumerate() {
+        let weighted_sum: f64 = configs
+            .iter()
+            .zip(&weighted)
+            .map(|(c, &(w, _))| w as f64 * (c.counts[group_idx] as f64 / group.max as f64))
+            .sum();
+        let prob = (weighted_sum / total_weight as f64) as f32;
+        for &cell in &group.cells {
+            probs.set_cell(cell, prob);
+        }
+    }
+
+    if let Some(total) = total_mines {
+        if num_outer > 0 {
+            let expected_remaining: f64 = weighted
+                .iter()
+                .map(|&(w, border_total)| w as f64 * (total - border_total) as f64)
+                .sum::<f64>()
+                / total_weight as f64;
+            let prob = (expected_remaining / num_outer as f64) as f32;
+            for &cell in &outer_cells {
+                probs.set_cell(cell, prob);
+            }
+        }
+    }
+
+    probs
 }
 
 // -----------------------------------------------------------------------------
@@ -99,10 +307,128 @@ fn find_probs(board: &Board, configs: &Vec<Config>) -> BoardProbs {
 
 impl Board {
     pub fn calc_probs(&self) -> BoardProbs {
-        let numbers = find_numbers(self);
-        let groups = find_groups(&numbers);
+        let mut numbers = find_numbers(self);
+        let groups = find_groups(&mut numbers);
         let configs = find_configs(&numbers, &groups);
-        find_probs(self, &configs)
+        find_probs(self, &groups, &configs, None)
+    }
+
+    /// As `calc_probs`, but given the total number of mines on the board so
+    /// that cells not bordering any number can be given a sensible
+    /// probability too.
+    pub fn calc_probs_with_mines(&self, total_mines: u32) -> BoardProbs {
+        let mut numbers = find_numbers(self);
+        let groups = find_groups(&mut numbers);
+        let configs = find_configs(&numbers, &groups);
+        find_probs(self, &groups, &configs, Some(total_mines))
+    }
+
+    /// Solve the board, returning both the cell probabilities and the sets
+    /// of cells that are definitely safe or definitely mines.
+    ///
+    /// Trivial deductions (a `0` frees its neighbours, a number equal to its
+    /// unclicked neighbour count flags them all) are found first and are
+    /// cheap. The full config enumeration then always runs over every
+    /// group; cells it proves that were already deduced trivially keep the
+    /// `Trivial` classification.
+    pub fn solve(&self) -> Solution {
+        let mut numbers = find_numbers(self);
+        let mut safe = HashMap::new();
+        let mut mines = HashMap::new();
+
+        for number in &numbers {
+            if number.value == 0 {
+                for &cell in &number.nbrs {
+                    safe.entry(cell).or_insert(Deduction::Trivial);
+                }
+            } else if number.value == number.nbrs.len() as u32 {
+                for &cell in &number.nbrs {
+                    mines.entry(cell).or_insert(Deduction::Trivial);
+                }
+            }
+        }
+
+        let groups = find_groups(&mut numbers);
+        let configs = find_configs(&numbers, &groups);
+        let probs = find_probs(self, &groups, &configs, None);
+
+        if !configs.is_empty() {
+            for (group_idx, group) in groups.iter().enumerate() {
+                if configs.iter().all(|c| c.counts[group_idx] == 0) {
+                    for &cell in &group.cells {
+                        safe.entry(cell).or_insert(Deduction::Logic);
+                    }
+                } else if configs.iter().all(|c| c.counts[group_idx] == group.max) {
+                    for &cell in &group.cells {
+                        mines.entry(cell).or_insert(Deduction::Logic);
+                    }
+                }
+            }
+        }
+
+        Solution { probs, safe, mines }
+    }
+
+    /// Repeatedly tighten every number's neighbour set until a fixed point
+    /// is reached, marking cells `Flagged` or `KnownSafe` wherever a single
+    /// number forces it. This is a cheap fast path that can be run before
+    /// resorting to the full config enumeration.
+    pub fn propagate(&self) -> Board {
+        let mut board = self.clone();
+        loop {
+            let mut changed = false;
+            // Numbers don't move between passes, but their reduced value
+            // does as neighbouring cells get annotated.
+            let num_coords: Vec<Coord> = board
+                .iter_cells()
+                .filter_map(|(c, v)| {
+                    if let CellContents::Num(_) = v {
+                        Some(c)
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            for coord in num_coords {
+                let value = match *board.cell(coord) {
+                    CellContents::Num(n) => n,
+                    _ => continue,
+                };
+                let mines: u32 = board
+                    .iter_neighbours(coord)
+                    .filter_map(|c| match board.cell(c) {
+                        CellContents::Mine(n) => Some(*n),
+                        CellContents::Flagged => Some(1),
+                        _ => None,
+                    })
+                    .sum();
+                if value < mines {
+                    panic!("Number {} in cell {} has too many neighbouring mines", value, coord)
+                }
+                let value = value - mines;
+                let unclicked: Vec<Coord> = board
+                    .iter_neighbours(coord)
+                    .filter(|&c| *board.cell(c) == CellContents::Unclicked)
+                    .collect();
+                if unclicked.is_empty() {
+                    continue;
+                }
+                if value == 0 {
+                    for &cell in &unclicked {
+                        board.set_cell(cell, CellContents::KnownSafe);
+                    }
+                    changed = true;
+                } else if value == unclicked.len() as u32 {
+                    for &cell in &unclicked {
+                        board.set_cell(cell, CellContents::Flagged);
+                    }
+                    changed = true;
+                }
+            }
+            if !changed {
+                return board;
+            }
+        }
     }
 }
 
@@ -115,11 +441,11 @@ mod test {
 
     fn make_board() -> Board {
         let mut board = Board::new(5, 3);
-        board.set_cell(Coord(1, 1), CellContents::Num(5));
-        board.set_cell(Coord(2, 0), CellContents::Mine(1));
-        board.set_cell(Coord(0, 1), CellContents::Mine(1));
-        board.set_cell(Coord(0, 0), CellContents::Mine(1));
-        board.set_cell(Coord(2, 1), CellContents::Num(2));
+        board.set_cell(Coord([1, 1]), CellContents::Num(5));
+        board.set_cell(Coord([2, 0]), CellContents::Mine(1));
+        board.set_cell(Coord([0, 1]), CellContents::Mine(1));
+        board.set_cell(Coord([0, 0]), CellContents::Mine(1));
+        board.set_cell(Coord([2, 1]), CellContents::Num(2));
         board
     }
 
@@ -136,9 +462,100 @@ mod test {
     #[test]
     fn find_groups() {
         let board = make_board();
-        let numbers = super::find_numbers(&board);
-        let groups = super::find_groups(&numbers);
+        let mut numbers = super::find_numbers(&board);
+        let groups = super::find_groups(&mut numbers);
         println!("{:#?}", groups);
         println!();
     }
+
+    #[test]
+    fn binomial() {
+        assert_eq!(super::binomial(5, 0), 1);
+        assert_eq!(super::binomial(5, 5), 1);
+        assert_eq!(super::binomial(5, 2), 10);
+        assert_eq!(super::binomial(2, 5), 0);
+    }
+
+    #[test]
+    fn calc_probs_single_number() {
+        // A single `1` with two unclicked neighbours: each has a 50% chance
+        // of being a mine.
+        let mut board = Board::new(3, 1);
+        board.set_cell(Coord([1, 0]), CellContents::Num(1));
+        let probs = board.calc_probs();
+        assert_eq!(*probs.cell(Coord([0, 0])), 0.5);
+        assert_eq!(*probs.cell(Coord([2, 0])), 0.5);
+        assert_eq!(*probs.cell(Coord([1, 0])), 0.0);
+    }
+
+    #[test]
+    fn calc_probs_no_numbers() {
+        let board = Board::new(3, 1);
+        let probs = board.calc_probs();
+        for (_, p) in probs.iter_cells() {
+            assert_eq!(*p, 0.0);
+        }
+    }
+
+    #[test]
+    fn calc_probs_with_mines() {
+        // A single `1` with two bordering cells and one outer cell, told
+        // there's exactly 1 mine on the board in total.
+        let mut board = Board::new(4, 1);
+        board.set_cell(Coord([1, 0]), CellContents::Num(1));
+        let probs = board.calc_probs_with_mines(1);
+        // The two bordering cells split the single mine between them...
+        assert_eq!(*probs.cell(Coord([0, 0])), 0.5);
+        assert_eq!(*probs.cell(Coord([2, 0])), 0.5);
+        // ...leaving the outer cell guaranteed safe.
+        assert_eq!(*probs.cell(Coord([3, 0])), 0.0);
+    }
+
+    #[test]
+    fn solve_trivial() {
+        // A `0` frees its neighbours without needing any enumeration.
+        let mut board = Board::new(3, 1);
+        board.set_cell(Coord([1, 0]), CellContents::Num(0));
+        let solution = board.solve();
+        assert_eq!(solution.safe[&Coord([0, 0])], Deduction::Trivial);
+        assert_eq!(solution.safe[&Coord([2, 0])], Deduction::Trivial);
+        assert!(solution.mines.is_empty());
+    }
+
+    #[test]
+    fn solve_logic() {
+        // The classic "1-2-1" pattern: none of the three numbers alone
+        // resolves any of their neighbours, but together they force the
+        // two end cells to be mines and the middle cell to be safe.
+        let mut board = Board::new(3, 2);
+        board.set_cell(Coord([0, 1]), CellContents::Num(1));
+        board.set_cell(Coord([1, 1]), CellContents::Num(2));
+        board.set_cell(Coord([2, 1]), CellContents::Num(1));
+        let solution = board.solve();
+        assert_eq!(solution.mines[&Coord([0, 0])], Deduction::Logic);
+        assert_eq!(solution.safe[&Coord([1, 0])], Deduction::Logic);
+        assert_eq!(solution.mines[&Coord([2, 0])], Deduction::Logic);
+    }
+
+    #[test]
+    fn propagate_single_pass() {
+        let mut board = Board::new(3, 1);
+        board.set_cell(Coord([1, 0]), CellContents::Num(0));
+        let propagated = board.propagate();
+        assert_eq!(*propagated.cell(Coord([0, 0])), CellContents::KnownSafe);
+        assert_eq!(*propagated.cell(Coord([2, 0])), CellContents::KnownSafe);
+    }
+
+    #[test]
+    fn propagate_to_fixed_point() {
+        // `B`'s `0` only frees its one neighbour in the first pass; only
+        // once that neighbour is known safe does `A`'s `1` have a single
+        // remaining unclicked neighbour to flag, which takes a second pass.
+        let mut board = Board::new(4, 1);
+        board.set_cell(Coord([1, 0]), CellContents::Num(1)); // A
+        board.set_cell(Coord([3, 0]), CellContents::Num(0)); // B
+        let propagated = board.propagate();
+        assert_eq!(*propagated.cell(Coord([2, 0])), CellContents::KnownSafe);
+        assert_eq!(*propagated.cell(Coord([0, 0])), CellContents::Flagged);
+    }
 }
@@ -40,7 +40,13 @@ pub unsafe extern "C" fn calc_probs(
         }
     };
     // println!("Board: {} x {}", board.x_size, board.y_size);
-    let probs: BoardProbs = board.calc_probs();
+    // A negative mine count means the total is unknown, so fall back to the
+    // unconstrained calculation.
+    let probs: BoardProbs = if c_board.num_mines >= 0 {
+        board.calc_probs_with_mines(c_board.num_mines as u32)
+    } else {
+        board.calc_probs()
+    };
 
     // println!("Probs: ");
     for (i, (_, p)) in probs.iter_cells().enumerate() {
@@ -4,13 +4,17 @@ use std::cmp::min;
 use std::collections::HashSet;
 use std::default::Default;
 use std::fmt;
-use std::vec;
+use std::ops::Range;
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum CellContents {
     Unclicked,
     Num(u32),
     Mine(u32),
+    /// A user-asserted mine, as opposed to a revealed `Mine(n)`.
+    Flagged,
+    /// A cell logically proven safe, but not yet clicked.
+    KnownSafe,
 }
 
 impl Default for CellContents {
@@ -19,127 +23,327 @@ impl Default for CellContents {
     }
 }
 
-pub struct Grid<T: Clone + Default> {
-    x_size: u32,
-    y_size: u32,
-    cells: Vec<T>,
-}
-
+/// A coordinate in an `N`-dimensional grid, one value per axis.
+///
+/// Defaults to 2 dimensions, since that's by far the most common case.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
-pub struct Coord(pub u32, pub u32);
+pub struct Coord<const N: usize = 2>(pub [u32; N]);
 
-impl fmt::Display for Coord {
+impl<const N: usize> fmt::Display for Coord<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}, {})", self.0, self.1)
+        write!(f, "(")?;
+        for (i, x) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", x)?;
+        }
+        write!(f, ")")
     }
 }
 
 #[cfg(test)]
-impl fmt::Debug for Coord {
+impl<const N: usize> fmt::Debug for Coord<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Coord({}, {})", self.0, self.1)
+        write!(f, "Coord{}", self)
+    }
+}
+
+fn coord_to_index<const N: usize>(coord: &Coord<N>, dims: &[u32; N]) -> usize {
+    let mut index: u64 = 0;
+    let mut stride: u64 = 1;
+    for i in 0..N {
+        index += coord.0[i] as u64 * stride;
+        stride *= dims[i] as u64;
+    }
+    index as usize
+}
+
+fn coord_from_index<const N: usize>(index: usize, dims: &[u32; N]) -> Coord<N> {
+    let mut coord = [0u32; N];
+    let mut remaining = index as u64;
+    for i in 0..N {
+        if i + 1 < N {
+            coord[i] = (remaining % dims[i] as u64) as u32;
+            remaining /= dims[i] as u64;
+        } else {
+            // Don't wrap the last axis, so an index at or beyond the grid's
+            // total size produces an out-of-range coordinate instead of
+            // silently wrapping - letting `check_coord` catch it.
+            coord[i] = remaining as u32;
+        }
     }
+    Coord(coord)
 }
 
-/// Grid implementation
+/// An `N`-dimensional grid of cells, stored as a flat `Vec`.
 ///
-/// Methods that accept an index or coordinate will panic if the given arg is
-/// out of bounds.
-impl<T: Clone + Default> Grid<T> {
-    pub fn new(x_size: u32, y_size: u32) -> Self {
-        if x_size < 1 || y_size < 1 {
-            panic!("Both dimensions must be nonzero");
+/// Defaults to 2 dimensions. Methods that accept an index or coordinate will
+/// panic if the given arg is out of bounds, except `get` and `get_mut`, which
+/// return `None` instead.
+#[derive(Clone)]
+pub struct Grid<T: Clone + Default, const N: usize = 2> {
+    dims: [u32; N],
+    cells: Vec<T>,
+}
+
+impl<T: Clone + Default, const N: usize> Grid<T, N> {
+    pub fn with_dims(dims: [u32; N]) -> Self {
+        if dims.iter().any(|&d| d < 1) {
+            panic!("All dimensions must be nonzero");
         }
+        let num_cells = dims.iter().product::<u32>() as usize;
         Self {
-            x_size,
-            y_size,
-            cells: vec![T::default(); (x_size * y_size) as usize],
+            dims,
+            cells: vec![T::default(); num_cells],
         }
     }
 
-    pub fn x_size(&self) -> u32 {
-        self.x_size
-    }
-
-    pub fn y_size(&self) -> u32 {
-        self.y_size
+    pub fn dims(&self) -> &[u32; N] {
+        &self.dims
     }
 
     pub fn num_cells(&self) -> u32 {
-        self.x_size * self.y_size
+        self.dims.iter().product()
     }
 
-    pub fn cell(&self, coord: Coord) -> &T {
+    pub fn cell(&self, coord: Coord<N>) -> &T {
         self.check_coord(&coord);
         &self.cells[self.coord_to_index(&coord)]
     }
 
-    pub fn iter_coords(&self) -> Vec<Coord> {
-        let mut vec = Vec::new();
-        for y in 0..self.y_size {
-            for x in 0..self.x_size {
-                vec.push(Coord(x, y));
-            }
+    /// As `cell`, but returning `None` instead of panicking if `coord` is
+    /// out of bounds.
+    pub fn get(&self, coord: Coord<N>) -> Option<&T> {
+        if self.has_coord(&coord) {
+            Some(&self.cells[self.coord_to_index(&coord)])
+        } else {
+            None
         }
-        vec
     }
 
-    pub fn iter_cells(&self) -> vec::IntoIter<(Coord, &T)> {
-        let mut vec = Vec::new();
-        for y in 0..self.y_size {
-            for x in 0..self.x_size {
-                let coord = Coord(x, y);
-                vec.push((coord, self.cell(coord)));
-            }
+    /// As `get`, but returning a mutable reference.
+    pub fn get_mut(&mut self, coord: Coord<N>) -> Option<&mut T> {
+        if self.has_coord(&coord) {
+            let index = self.coord_to_index(&coord);
+            Some(&mut self.cells[index])
+        } else {
+            None
+        }
+    }
+
+    pub fn iter_coords(&self) -> CoordIter<N> {
+        CoordIter {
+            dims: self.dims,
+            total: self.num_cells(),
+            index: 0,
+        }
+    }
+
+    pub fn iter_cells(&self) -> CellIter<'_, T, N> {
+        CellIter {
+            grid: self,
+            coords: self.iter_coords(),
         }
-        vec.into_iter()
     }
 
-    pub fn has_coord(&self, coord: &Coord) -> bool {
-        coord.0 < self.x_size && coord.1 < self.y_size
+    pub fn has_coord(&self, coord: &Coord<N>) -> bool {
+        (0..N).all(|i| coord.0[i] < self.dims[i])
     }
 
-    fn check_coord(&self, coord: &Coord) {
+    fn check_coord(&self, coord: &Coord<N>) {
         if !self.has_coord(coord) {
             panic!("Coord out of bounds");
         }
     }
 
-    pub fn set_cell(&mut self, coord: Coord, contents: T) {
+    pub fn set_cell(&mut self, coord: Coord<N>, contents: T) {
         let index = self.coord_to_index(&coord);
         self.cells[index] = contents;
     }
 
-    pub fn coord_to_index(&self, coord: &Coord) -> usize {
+    pub fn coord_to_index(&self, coord: &Coord<N>) -> usize {
         self.check_coord(coord);
-        (coord.0 + coord.1 * self.x_size) as usize
+        coord_to_index(coord, &self.dims)
     }
 
-    pub fn coord_from_index(&self, index: usize) -> Coord {
-        let index = index as u32;
-        let coord = Coord(index % self.x_size, index / self.x_size);
+    pub fn coord_from_index(&self, index: usize) -> Coord<N> {
+        let coord = coord_from_index(index, &self.dims);
         self.check_coord(&coord);
         coord
     }
 
-    /// Get a list of the coordinates of neighbouring cells.
-    pub fn get_neighbours(&self, coord: Coord) -> HashSet<Coord> {
+    /// Get a list of the coordinates of neighbouring cells, i.e. those
+    /// differing by -1, 0 or +1 on each axis (excluding the cell itself).
+    pub fn get_neighbours(&self, coord: Coord<N>) -> HashSet<Coord<N>> {
+        self.iter_neighbours(coord).collect()
+    }
+
+    /// As `get_neighbours`, but lazy rather than allocating a `HashSet` -
+    /// useful since it's called once per number and the
+    /// allocation otherwise dominates the hot path for large boards.
+    pub fn iter_neighbours(&self, coord: Coord<N>) -> NeighbourIter<N> {
         self.check_coord(&coord);
-        let Coord(x, y) = coord;
-        let x_min = if x >= 1 { x - 1 } else { 0 };
-        let x_max = min(self.x_size - 1, x + 1);
-        let y_min = if y >= 1 { y - 1 } else { 0 };
-        let y_max = min(self.y_size - 1, y + 1);
-
-        let mut nbrs = HashSet::new();
-        for j in y_min..=y_max {
-            for i in x_min..=x_max {
-                if (x, y) != (i, j) {
-                    nbrs.insert(Coord(i, j));
-                }
+        NeighbourIter::new(coord.0, self.dims)
+    }
+}
+
+impl<T: Clone + Default> Grid<T, 2> {
+    pub fn new(x_size: u32, y_size: u32) -> Self {
+        Self::with_dims([x_size, y_size])
+    }
+
+    pub fn x_size(&self) -> u32 {
+        self.dims[0]
+    }
+
+    pub fn y_size(&self) -> u32 {
+        self.dims[1]
+    }
+
+    /// Copy out the region of the grid covered by `rect`. Any part of
+    /// `rect` outside the grid is filled with the default value.
+    pub fn subgrid(&self, rect: Rect) -> Self {
+        let mut grid = Self::new(rect.w, rect.h);
+        for coord in self.iter_rect(rect) {
+            let contents = self.cell(coord).clone();
+            grid.set_cell(Coord([coord.0[0] - rect.x, coord.0[1] - rect.y]), contents);
+        }
+        grid
+    }
+
+    /// Iterate over the coordinates of `rect` that lie within the grid.
+    pub fn iter_rect(&self, rect: Rect) -> impl Iterator<Item = Coord> + '_ {
+        rect.y_range().flat_map(move |y| {
+            rect.x_range()
+                .filter_map(move |x| Some(Coord([x, y])).filter(|c| self.has_coord(c)))
+        })
+    }
+}
+
+/// Lazily yields every `Coord` in a grid, in row-major (axis-0-fastest)
+/// order.
+pub struct CoordIter<const N: usize = 2> {
+    dims: [u32; N],
+    total: u32,
+    index: u32,
+}
+
+impl<const N: usize> Iterator for CoordIter<N> {
+    type Item = Coord<N>;
+
+    fn next(&mut self) -> Option<Coord<N>> {
+        if self.index >= self.total {
+            return None;
+        }
+        let coord = coord_from_index(self.index as usize, &self.dims);
+        self.index += 1;
+        Some(coord)
+    }
+}
+
+/// Lazily yields every `(Coord, &T)` pair in a grid, in row-major order.
+pub struct CellIter<'a, T: Clone + Default, const N: usize = 2> {
+    grid: &'a Grid<T, N>,
+    coords: CoordIter<N>,
+}
+
+impl<'a, T: Clone + Default, const N: usize> Iterator for CellIter<'a, T, N> {
+    type Item = (Coord<N>, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.coords.next().map(|c| (c, self.grid.cell(c)))
+    }
+}
+
+/// Lazily yields the coordinates neighbouring a cell, i.e. every cell
+/// differing from it by -1, 0 or +1 on each axis, excluding the cell itself
+/// and clipped to the grid bounds.
+pub struct NeighbourIter<const N: usize = 2> {
+    centre: [u32; N],
+    lo: [u32; N],
+    hi: [u32; N],
+    cur: [u32; N],
+    done: bool,
+}
+
+impl<const N: usize> NeighbourIter<N> {
+    fn new(centre: [u32; N], dims: [u32; N]) -> Self {
+        let mut lo = [0u32; N];
+        let mut hi = [0u32; N];
+        for i in 0..N {
+            lo[i] = if centre[i] >= 1 { centre[i] - 1 } else { 0 };
+            hi[i] = min(dims[i] - 1, centre[i] + 1);
+        }
+        Self {
+            centre,
+            lo,
+            hi,
+            cur: lo,
+            done: false,
+        }
+    }
+
+    /// Advance `cur` to the next point in the bounding box, odometer-style.
+    fn advance(&mut self) {
+        for i in 0..N {
+            if self.cur[i] < self.hi[i] {
+                self.cur[i] += 1;
+                return;
+            }
+            self.cur[i] = self.lo[i];
+        }
+        self.done = true;
+    }
+}
+
+impl<const N: usize> Iterator for NeighbourIter<N> {
+    type Item = Coord<N>;
+
+    fn next(&mut self) -> Option<Coord<N>> {
+        while !self.done {
+            let candidate = self.cur;
+            self.advance();
+            if candidate != self.centre {
+                return Some(Coord(candidate));
             }
         }
-        nbrs
+        None
+    }
+}
+
+/// An axis-aligned rectangular region of a 2D grid.
+#[derive(Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        coord.0[0] >= self.x
+            && coord.0[0] < self.x + self.w
+            && coord.0[1] >= self.y
+            && coord.0[1] < self.y + self.h
+    }
+
+    pub fn area(&self) -> u32 {
+        self.w * self.h
+    }
+
+    pub fn x_range(&self) -> Range<u32> {
+        self.x..self.x + self.w
+    }
+
+    pub fn y_range(&self) -> Range<u32> {
+        self.y..self.y + self.h
     }
 }
 
@@ -150,15 +354,17 @@ impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // The `f` value implements the `Write` trait, which is what the
         // write!() macro is expecting.
-        for j in 0..self.y_size {
-            for i in 0..self.x_size {
-                let cell = &self.cell(Coord(i, j));
+        for j in 0..self.y_size() {
+            for i in 0..self.x_size() {
+                let cell = &self.cell(Coord([i, j]));
                 let ch: String; // Character representation
                 match cell {
                     CellContents::Unclicked => ch = format!("#"),
                     CellContents::Num(0) => ch = format!("."),
                     CellContents::Num(n) => ch = format!("{}", n),
                     CellContents::Mine(_) => ch = format!("M"),
+                    CellContents::Flagged => ch = format!("F"),
+                    CellContents::KnownSafe => ch = format!("S"),
                 }
                 write!(f, "{} ", ch)?;
             }
@@ -185,15 +391,15 @@ mod test {
         #[test]
         fn coord_to_index() {
             let grid = Grid::<u32>::new(5, 3);
-            assert_eq!(grid.coord_to_index(&Coord(0, 0)), 0);
-            assert_eq!(grid.coord_to_index(&Coord(3, 1)), 8);
+            assert_eq!(grid.coord_to_index(&Coord([0, 0])), 0);
+            assert_eq!(grid.coord_to_index(&Coord([3, 1])), 8);
         }
 
         #[test]
         fn coord_to_index_panic() {
             let grid = Grid::<u32>::new(5, 3);
             for c in &[(5, 0), (0, 3), (5, 3), (6, 20), (100, 100)] {
-                assert_panics!(grid.coord_to_index(&Coord(c.0, c.1)));
+                assert_panics!(grid.coord_to_index(&Coord([c.0, c.1])));
             }
         }
 
@@ -217,32 +423,87 @@ mod test {
         fn get_neighbours() {
             let grid = Grid::<u32>::new(5, 3);
             assert_eq!(
-                grid.get_neighbours(Coord(0, 0)),
-                HashSet::from_iter(vec![Coord(1, 0), Coord(0, 1), Coord(1, 1)])
+                grid.get_neighbours(Coord([0, 0])),
+                HashSet::from_iter(vec![Coord([1, 0]), Coord([0, 1]), Coord([1, 1])])
             );
             assert_eq!(
-                grid.get_neighbours(Coord(2, 1)),
+                grid.get_neighbours(Coord([2, 1])),
                 HashSet::from_iter(vec![
-                    Coord(1, 0),
-                    Coord(1, 1),
-                    Coord(1, 2),
-                    Coord(2, 0),
-                    Coord(2, 2),
-                    Coord(3, 0),
-                    Coord(3, 1),
-                    Coord(3, 2),
+                    Coord([1, 0]),
+                    Coord([1, 1]),
+                    Coord([1, 2]),
+                    Coord([2, 0]),
+                    Coord([2, 2]),
+                    Coord([3, 0]),
+                    Coord([3, 1]),
+                    Coord([3, 2]),
                 ])
             );
             assert_eq!(
-                grid.get_neighbours(Coord(4, 1)),
+                grid.get_neighbours(Coord([4, 1])),
                 HashSet::from_iter(vec![
-                    Coord(3, 0),
-                    Coord(3, 1),
-                    Coord(3, 2),
-                    Coord(4, 0),
-                    Coord(4, 2),
+                    Coord([3, 0]),
+                    Coord([3, 1]),
+                    Coord([3, 2]),
+                    Coord([4, 0]),
+                    Coord([4, 2]),
                 ])
             );
         }
+
+        #[test]
+        fn get() {
+            let grid = Grid::<u32>::new(5, 3);
+            assert_eq!(grid.get(Coord([0, 0])), Some(&0));
+            assert_eq!(grid.get(Coord([5, 0])), None);
+            assert_eq!(grid.get(Coord([0, 3])), None);
+        }
+
+        #[test]
+        fn get_mut() {
+            let mut grid = Grid::<u32>::new(5, 3);
+            *grid.get_mut(Coord([1, 1])).unwrap() = 7;
+            assert_eq!(grid.get(Coord([1, 1])), Some(&7));
+            assert_eq!(grid.get_mut(Coord([5, 3])), None);
+        }
+
+        #[test]
+        fn rect_contains() {
+            let rect = Rect::new(1, 1, 2, 2);
+            assert!(rect.contains(Coord([1, 1])));
+            assert!(rect.contains(Coord([2, 2])));
+            assert!(!rect.contains(Coord([3, 1])));
+            assert!(!rect.contains(Coord([0, 1])));
+            assert_eq!(rect.area(), 4);
+        }
+
+        #[test]
+        fn subgrid() {
+            let mut grid = Grid::<u32>::new(5, 3);
+            grid.set_cell(Coord([1, 1]), 1);
+            grid.set_cell(Coord([2, 1]), 2);
+            let sub = grid.subgrid(Rect::new(1, 1, 2, 2));
+            assert_eq!(*sub.cell(Coord([0, 0])), 1);
+            assert_eq!(*sub.cell(Coord([1, 0])), 2);
+            assert_eq!(*sub.cell(Coord([0, 1])), 0);
+        }
+
+        #[test]
+        fn iter_rect_clips_to_grid() {
+            let grid = Grid::<u32>::new(5, 3);
+            let coords: HashSet<Coord> = grid.iter_rect(Rect::new(4, 2, 3, 3)).collect();
+            assert_eq!(coords, HashSet::from_iter(vec![Coord([4, 2])]));
+        }
+
+        #[test]
+        fn three_dimensions() {
+            // The grid's coordinate and neighbour logic works for higher
+            // dimensions too - a 2x2x2 grid has 7 neighbours for any given
+            // cell. The solver in logic.rs works on the 2D `Board` only.
+            let grid = Grid::<u32, 3>::with_dims([2, 2, 2]);
+            assert_eq!(grid.num_cells(), 8);
+            assert_eq!(grid.get_neighbours(Coord([0, 0, 0])).len(), 7);
+            assert_eq!(grid.iter_coords().count(), 8);
+        }
     }
 }